@@ -1,6 +1,8 @@
 #![allow(unused_imports)]
 
-use rpgsanta_2022::GameData;
+use rpgsanta_2022::{
+  Command, Direction, GameData, MetricRegistry, PlayerId, RoomID, Storage,
+};
 use serenity::{
   async_trait,
   http::Http,
@@ -13,7 +15,7 @@ use serenity::{
 };
 use std::{
   collections::{hash_map::Entry, HashMap},
-  path::{Path, PathBuf},
+  fmt::Write,
   sync::Arc,
   time::Duration,
 };
@@ -38,7 +40,15 @@ macro_rules! log_err {
   };
 }
 
-type SessionsMap = Arc<RwLock<HashMap<ChannelId, MspcSender<String>>>>;
+/// A message delivered into a session's channel: either raw player input to
+/// run through `process_input`, or an out-of-band line from another player
+/// sharing the same room that should just be relayed as-is.
+enum SessionMessage {
+  Input(String),
+  Announcement(String),
+}
+
+type SessionsMap = Arc<RwLock<HashMap<ChannelId, MspcSender<SessionMessage>>>>;
 
 /// Manages text adventure sessions over discord.
 ///
@@ -51,39 +61,211 @@ type SessionsMap = Arc<RwLock<HashMap<ChannelId, MspcSender<String>>>>;
 ///   "live" and the async task has the latest version of the game data.
 /// * OR there is not id/sender in the map, in which case the data on disk is
 ///   the latest version.
-#[derive(Default)]
 struct TextBot {
   sessions: SessionsMap,
+  storage: Storage,
+  rooms: RoomRegistry,
+  metrics: MetricRegistry,
+}
+
+type RoomSubscribers = HashMap<ChannelId, MspcSender<SessionMessage>>;
+type RoomsMap = Arc<RwLock<HashMap<RoomID, RoomSubscribers>>>;
+
+/// Lets every session currently standing in the same `RoomID` broadcast
+/// out-of-band lines (entering/leaving/saying something) to each other.
+///
+/// Joining is a check-then-insert under the write lock, same as the
+/// `Entry::Vacant` pattern `SessionsMap` already uses, and the last session
+/// to leave a room removes that room's now-empty entry.
+#[derive(Clone, Default)]
+struct RoomRegistry {
+  rooms: RoomsMap,
+}
+impl RoomRegistry {
+  async fn join(
+    &self, room: RoomID, channel_id: ChannelId,
+    sender: MspcSender<SessionMessage>,
+  ) {
+    match self.rooms.write().await.entry(room) {
+      Entry::Occupied(mut o) => {
+        o.get_mut().insert(channel_id, sender);
+      }
+      Entry::Vacant(v) => {
+        v.insert(HashMap::from([(channel_id, sender)]));
+      }
+    }
+  }
+
+  async fn leave(&self, room: RoomID, channel_id: ChannelId) {
+    if let Entry::Occupied(mut o) = self.rooms.write().await.entry(room) {
+      o.get_mut().remove(&channel_id);
+      if o.get().is_empty() {
+        o.remove();
+      }
+    }
+  }
+
+  async fn broadcast(&self, room: RoomID, except: ChannelId, message: String) {
+    // Collect the senders and drop the read guard before awaiting any of the
+    // sends below: these channels are bounded, so awaiting a send while
+    // holding the lock would stall every `join`/`leave` behind a full buffer.
+    let senders: Vec<_> = match self.rooms.read().await.get(&room) {
+      Some(subscribers) => subscribers
+        .iter()
+        .filter(|(channel_id, _)| **channel_id != except)
+        .map(|(_, sender)| sender.clone())
+        .collect(),
+      None => return,
+    };
+    for sender in senders.iter() {
+      log_err!(
+        sender.send(SessionMessage::Announcement(message.clone())).await
+      );
+    }
+  }
 }
 
 #[inline]
+#[allow(clippy::too_many_arguments)]
 async fn do_one_input(
-  input: String, channel_id: ChannelId, game: &mut GameData, http: &Arc<Http>,
+  input: String, channel_id: ChannelId, display_name: &str, game: &mut GameData,
+  player: &mut Option<PlayerId>, http: &Arc<Http>, storage: &Storage,
+  rooms: &RoomRegistry, sender: &MspcSender<SessionMessage>,
+  metrics: &MetricRegistry,
 ) {
   drop(channel_id.broadcast_typing(http).await);
-  let response = game.process_input(input);
-  //println!("{response}");
-  log_err!(channel_id.say(http, response).await);
-  let profile_bytes = match Vec::<u8>::try_from(&*game) {
-    Ok(bytes) => bytes,
-    Err(why) => {
-      println!("Couldn't serialize profile data: {why}");
+
+  let command = Command::parse(&input);
+  match command.clone() {
+    Command::Register { name, password } => {
+      let reply = match storage.register_player(&name, &password).await {
+        Ok(id) => {
+          *player = Some(id);
+          format!("Registered and logged in as {name}.")
+        }
+        Err(why) => format!("Couldn't register: {why}"),
+      };
+      log_err!(channel_id.say(http, reply).await);
       return;
     }
-  };
-  log_err!(store_profile_bytes(channel_id, &profile_bytes));
+    Command::Login { name, password } => {
+      let reply = match storage.login_player(&name, &password).await {
+        Ok(id) => {
+          *player = Some(id);
+          if let Ok(Some(saved)) = storage.load_player_profile(id).await {
+            let room_before = game.current_room_id();
+            *game = saved;
+            let room_after = game.current_room_id();
+            if room_after != room_before {
+              rooms.leave(room_before, channel_id).await;
+              rooms
+                .broadcast(
+                  room_before,
+                  channel_id,
+                  format!("{display_name} leaves."),
+                )
+                .await;
+              rooms.join(room_after, channel_id, sender.clone()).await;
+              rooms
+                .broadcast(
+                  room_after,
+                  channel_id,
+                  format!("{display_name} enters."),
+                )
+                .await;
+            }
+          }
+          format!("Logged in as {name}.")
+        }
+        Err(why) => format!("Login failed: {why}"),
+      };
+      log_err!(channel_id.say(http, reply).await);
+      return;
+    }
+    _ => {}
+  }
+
+  let room_before = game.current_room_id();
+  let timer = metrics.process_input_latency.start_timer();
+  let response = game.process_input(&input);
+  timer.observe_duration();
+  metrics.inputs_total.inc();
+  //println!("{response}");
+  log_err!(channel_id.say(http, &response).await);
+
+  if let Command::Go(dir) = command {
+    let room_after = game.current_room_id();
+    if room_after != room_before {
+      rooms.leave(room_before, channel_id).await;
+      rooms
+        .broadcast(
+          room_before,
+          channel_id,
+          format!("{display_name} leaves to the {}.", dir.name()),
+        )
+        .await;
+      rooms.join(room_after, channel_id, sender.clone()).await;
+      rooms
+        .broadcast(
+          room_after,
+          channel_id,
+          format!("{display_name} enters from the {}.", dir.opposite().name()),
+        )
+        .await;
+    }
+  } else if let Command::Say(text) = command {
+    rooms
+      .broadcast(
+        game.current_room_id(),
+        channel_id,
+        format!("{display_name} says, \"{text}\""),
+      )
+      .await;
+  }
+
+  match *player {
+    Some(id) => {
+      log_err!(storage.store_player_profile(id, game).await);
+      log_err!(
+        storage
+          .record_player_turn(id, game.turn_index(), &input, &response)
+          .await
+      );
+    }
+    None => {
+      log_err!(storage.store_profile(channel_id.0, game).await);
+      log_err!(
+        storage
+          .record_turn(channel_id.0, game.turn_index(), &input, &response)
+          .await
+      );
+    }
+  }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn perform_game(
-  channel_id: ChannelId, mut recver: MspcReceiver<String>, mut game: GameData,
-  sessions: SessionsMap, http: Arc<Http>,
+  channel_id: ChannelId, display_name: String,
+  mut recver: MspcReceiver<SessionMessage>, mut game: GameData,
+  sessions: SessionsMap, http: Arc<Http>, storage: Storage, rooms: RoomRegistry,
+  sender: MspcSender<SessionMessage>, metrics: MetricRegistry,
 ) {
   const LIMIT: Duration = Duration::new(60 * 10, 0);
+  let mut player: Option<PlayerId> = None;
+
+  rooms.join(game.current_room_id(), channel_id, sender.clone()).await;
 
   loop {
     match timeout(LIMIT, recver.recv()).await {
-      Ok(Some(input)) => {
-        do_one_input(input, channel_id, &mut game, &http).await
+      Ok(Some(SessionMessage::Input(input))) => {
+        do_one_input(
+          input, channel_id, &display_name, &mut game, &mut player, &http,
+          &storage, &rooms, &sender, &metrics,
+        )
+        .await
+      }
+      Ok(Some(SessionMessage::Announcement(text))) => {
+        log_err!(channel_id.say(&http, text).await);
       }
       Ok(None) => {
         // This case means the channel was closed? This shouldn't be possible,
@@ -98,24 +280,23 @@ async fn perform_game(
 
   let mut write_lock = sessions.write().await;
   recver.close();
-  while let Some(input) = recver.recv().await {
-    do_one_input(input, channel_id, &mut game, &http).await
+  while let Some(message) = recver.recv().await {
+    match message {
+      SessionMessage::Input(input) => {
+        do_one_input(
+          input, channel_id, &display_name, &mut game, &mut player, &http,
+          &storage, &rooms, &sender, &metrics,
+        )
+        .await
+      }
+      SessionMessage::Announcement(text) => {
+        log_err!(channel_id.say(&http, text).await);
+      }
+    }
   }
+  rooms.leave(game.current_room_id(), channel_id).await;
   write_lock.remove(&channel_id);
-}
-
-fn save_path_for_id(ChannelId(id): ChannelId) -> PathBuf {
-  Path::new("save_data").join(format!("{id}.data"))
-}
-fn load_profile_bytes(channel_id: ChannelId) -> std::io::Result<Vec<u8>> {
-  std::fs::read(save_path_for_id(channel_id))
-}
-fn store_profile_bytes(
-  channel_id: ChannelId, bytes: &[u8],
-) -> std::io::Result<()> {
-  let p = save_path_for_id(channel_id);
-  std::fs::create_dir_all(p.parent().unwrap_or(Path::new(""))).ok();
-  std::fs::write(p, bytes)
+  metrics.live_sessions.dec();
 }
 
 #[async_trait]
@@ -147,49 +328,111 @@ impl EventHandler for TextBot {
       return;
     }
 
-    //let author = msg.author;
-    //let author_name = author.name.as_str();
-    //let author_discriminator = author.discriminator;
-    //let content = msg.content.as_str();
-    //println!("{author_name}#{author_discriminator}$ {content}");
-
+    let display_name = msg.author.name.clone();
     let channel_id = msg.channel_id;
     let r = self.sessions.read().await;
     if let Some(sender) = r.get(&channel_id) {
-      log_err!(sender.send(msg.content).await);
-    } else {
-      drop(r);
-      match self.sessions.write().await.entry(channel_id) {
-        Entry::Occupied(o) => {
-          let sender = o.get();
-          log_err!(sender.send(msg.content).await);
-        }
-        Entry::Vacant(v) => {
-          let (sender, recver) = mpsc_channel(5);
-          let ses = Arc::clone(&self.sessions);
-          let http = Arc::clone(&ctx.http);
-          let bytes = load_profile_bytes(channel_id).unwrap_or_default();
-          let game = GameData::try_from(bytes.as_ref()).unwrap_or_default();
-          task_spawn(perform_game(channel_id, recver, game, ses, http));
-          log_err!(sender.send(msg.content).await);
-          v.insert(sender);
+      log_err!(sender.send(SessionMessage::Input(msg.content)).await);
+      return;
+    }
+    drop(r);
+
+    // Do the profile load and welcome-back reply *before* taking the write
+    // lock below, so a slow storage round-trip or Discord API call never
+    // blocks first-contact handling for every other channel.
+    let loaded_game = match self.storage.load_profile(channel_id.0).await {
+      Ok(Some(mut game)) => {
+        if let Ok(turns) = self.storage.recent_turns(channel_id.0, 20).await {
+          if !turns.is_empty() {
+            let mut welcome_back =
+              "Welcome back! Here's where you left off:\n".to_string();
+            for turn in turns.iter().rev().take(5).rev() {
+              drop(write!(
+                welcome_back,
+                "> {}\n{}\n",
+                turn.input, turn.response
+              ));
+            }
+            log_err!(channel_id.say(&ctx.http, welcome_back).await);
+            game.seed_history(turns);
+          }
         }
+        game
+      }
+      Ok(None) => GameData::default(),
+      Err(why) => {
+        println!("Couldn't load profile data: {why}");
+        GameData::default()
+      }
+    };
+
+    match self.sessions.write().await.entry(channel_id) {
+      Entry::Occupied(o) => {
+        // Someone else set up this channel's session while we were loading
+        // above; just forward the input and let the loaded_game go to waste.
+        let sender = o.get();
+        log_err!(sender.send(SessionMessage::Input(msg.content)).await);
+      }
+      Entry::Vacant(v) => {
+        let (sender, recver) = mpsc_channel(5);
+        let ses = Arc::clone(&self.sessions);
+        let http = Arc::clone(&ctx.http);
+        let storage = self.storage.clone();
+        let rooms = self.rooms.clone();
+        let metrics = self.metrics.clone();
+        metrics.live_sessions.inc();
+        task_spawn(perform_game(
+          channel_id,
+          display_name,
+          recver,
+          loaded_game,
+          ses,
+          http,
+          storage,
+          rooms,
+          sender.clone(),
+          metrics,
+        ));
+        log_err!(sender.send(SessionMessage::Input(msg.content)).await);
+        v.insert(sender);
       }
     }
   }
 }
 
+const DEFAULT_DATABASE_URL: &str = "sqlite://save_data/game.db?mode=rwc";
+const DEFAULT_METRICS_BIND_ADDR: &str = "127.0.0.1:9898";
+
 #[tokio::main]
 async fn main() {
   let token =
     std::env::var("DISCORD_TOKEN").expect("Expected a `DISCORD_TOKEN` value");
+  let database_url = std::env::var("DATABASE_URL")
+    .unwrap_or_else(|_| DEFAULT_DATABASE_URL.to_string());
+  let metrics_bind_addr = std::env::var("METRICS_BIND_ADDR")
+    .unwrap_or_else(|_| DEFAULT_METRICS_BIND_ADDR.to_string());
 
   let intents = GatewayIntents::GUILD_MESSAGES
     | GatewayIntents::DIRECT_MESSAGES
     | GatewayIntents::MESSAGE_CONTENT;
 
+  let storage = Storage::connect(&database_url)
+    .await
+    .expect("Couldn't open the profile database");
+
+  let metrics = MetricRegistry::default();
+  let serving_metrics = metrics.clone();
+  task_spawn(async move {
+    log_err!(serving_metrics.serve(&metrics_bind_addr).await);
+  });
+
   let mut client = Client::builder(&token, intents)
-    .event_handler(TextBot::default())
+    .event_handler(TextBot {
+      sessions: SessionsMap::default(),
+      storage,
+      rooms: RoomRegistry::default(),
+      metrics,
+    })
     .await
     .expect("Err creating client");
 
@@ -0,0 +1,176 @@
+//! A minimal IRC server frontend.
+//!
+//! This speaks just enough of the IRC wire protocol (NICK/USER registration,
+//! JOIN, PRIVMSG as game input, QUIT) for a plain IRC client to connect and
+//! play. Each nick maps to its own `GameData` session loaded from the shared
+//! `Storage`, proving `process_input` is transport-independent: this file
+//! only translates IRC lines to/from it, the same way `discord_bot.rs`
+//! translates Discord messages.
+
+#![allow(unused_imports)]
+
+use rpgsanta_2022::{GameData, Storage};
+use std::{
+  collections::hash_map::DefaultHasher,
+  hash::{Hash, Hasher},
+};
+use tokio::{
+  io::{split, AsyncBufReadExt, AsyncWriteExt, BufReader},
+  net::{TcpListener, TcpStream},
+};
+
+const DEFAULT_DATABASE_URL: &str = "sqlite://save_data/game.db?mode=rwc";
+const DEFAULT_BIND_ADDR: &str = "127.0.0.1:6667";
+const SERVER_NAME: &str = "rpgsanta";
+
+/// Hashes a nick into the stable `channel_id` its profile is stored under, so
+/// the same save resumes every time that nick reconnects.
+fn channel_id_for_nick(nick: &str) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  nick.hash(&mut hasher);
+  hasher.finish()
+}
+
+/// One parsed line of the IRC wire protocol: a verb plus whatever args and
+/// trailing (`:`-prefixed) parameter followed it.
+struct IrcLine {
+  verb: String,
+  args: Vec<String>,
+  trailing: Option<String>,
+}
+impl IrcLine {
+  fn parse(line: &str) -> Option<IrcLine> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.is_empty() {
+      return None;
+    }
+    let (head, trailing) = match line.split_once(" :") {
+      Some((head, trailing)) => (head, Some(trailing.to_string())),
+      None => (line, None),
+    };
+    let mut parts = head.split(' ').filter(|s| !s.is_empty());
+    let verb = parts.next()?.to_ascii_uppercase();
+    let args = parts.map(str::to_string).collect();
+    Some(IrcLine { verb, args, trailing })
+  }
+}
+
+async fn write_line<W: AsyncWriteExt + Unpin>(writer: &mut W, line: &str) {
+  let _ = writer.write_all(line.as_bytes()).await;
+  let _ = writer.write_all(b"\r\n").await;
+}
+
+/// Sends the `001` welcome numeric. Real clients send `NICK` and `USER` in
+/// either order and expect this burst exactly once, after both have arrived.
+async fn send_welcome<W: AsyncWriteExt + Unpin>(writer: &mut W, nick: &str) {
+  write_line(
+    writer,
+    &format!(":{SERVER_NAME} 001 {nick} :Welcome to rpgsanta, {nick}"),
+  )
+  .await;
+}
+
+async fn handle_connection(stream: TcpStream, storage: Storage) {
+  let (read_half, mut write_half) = split(stream);
+  let mut lines = BufReader::new(read_half).lines();
+
+  let mut nick: Option<String> = None;
+  let mut game: Option<GameData> = None;
+  let mut user_seen = false;
+  let mut welcomed = false;
+
+  while let Ok(Some(line)) = lines.next_line().await {
+    let Some(parsed) = IrcLine::parse(&line) else { continue };
+    match parsed.verb.as_str() {
+      "NICK" => {
+        let Some(new_nick) = parsed.args.first() else { continue };
+        let loaded = match storage.load_profile(channel_id_for_nick(new_nick)).await
+        {
+          Ok(Some(g)) => g,
+          Ok(None) => GameData::default(),
+          Err(why) => {
+            println!("Couldn't load profile for {new_nick}: {why}");
+            GameData::default()
+          }
+        };
+        nick = Some(new_nick.clone());
+        game = Some(loaded);
+        if user_seen && !welcomed {
+          send_welcome(&mut write_half, new_nick).await;
+          welcomed = true;
+        }
+      }
+      "USER" => {
+        user_seen = true;
+        if !welcomed {
+          if let Some(n) = nick.clone() {
+            send_welcome(&mut write_half, &n).await;
+            welcomed = true;
+          }
+        }
+      }
+      "JOIN" => {
+        if let (Some(nick), Some(channel)) = (nick.as_ref(), parsed.args.first())
+        {
+          write_line(&mut write_half, &format!(":{nick} JOIN :{channel}")).await;
+        }
+      }
+      "PRIVMSG" => {
+        let (Some(nick), Some(game), Some(target), Some(input)) = (
+          nick.as_ref(),
+          game.as_mut(),
+          parsed.args.first(),
+          parsed.trailing.as_ref(),
+        ) else {
+          continue;
+        };
+        let response = game.process_input(input);
+        if let Err(why) =
+          storage.store_profile(channel_id_for_nick(nick), game).await
+        {
+          println!("Couldn't save profile for {nick}: {why}");
+        }
+        for reply_line in response.lines() {
+          write_line(
+            &mut write_half,
+            &format!(":{SERVER_NAME} PRIVMSG {target} :{reply_line}"),
+          )
+          .await;
+        }
+      }
+      "QUIT" => {
+        write_line(&mut write_half, &format!(":{SERVER_NAME} NOTICE * :Goodbye!"))
+          .await;
+        break;
+      }
+      _ => {}
+    }
+  }
+}
+
+#[tokio::main]
+async fn main() {
+  let database_url = std::env::var("DATABASE_URL")
+    .unwrap_or_else(|_| DEFAULT_DATABASE_URL.to_string());
+  let bind_addr =
+    std::env::var("IRC_BIND_ADDR").unwrap_or_else(|_| DEFAULT_BIND_ADDR.to_string());
+
+  let storage = Storage::connect(&database_url)
+    .await
+    .expect("Couldn't open the profile database");
+  let listener = TcpListener::bind(&bind_addr)
+    .await
+    .expect("Couldn't bind the IRC listener");
+  println!("IRC frontend listening on {bind_addr}");
+
+  loop {
+    let (stream, _) = match listener.accept().await {
+      Ok(pair) => pair,
+      Err(why) => {
+        println!("Couldn't accept connection: {why}");
+        continue;
+      }
+    };
+    tokio::spawn(handle_connection(stream, storage.clone()));
+  }
+}
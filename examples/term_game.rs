@@ -1,23 +1,24 @@
 #![allow(unused_imports)]
 
-use rpgsanta_2022::GameData;
-use std::{
-  io::{stdin, stdout, BufRead, Read, Write},
-  path::{Path, PathBuf},
-};
+use rpgsanta_2022::{GameData, Storage};
+use std::io::{stdin, stdout, BufRead, Read, Write};
 
-fn main() {
-  let bytes = match load_profile_bytes() {
-    Ok(bytes) => bytes,
-    Err(why) => {
-      println!("Couldn't read profile save file: {why:?}");
-      Vec::default()
-    }
-  };
-  let mut game = match GameData::try_from(bytes.as_ref()) {
-    Ok(game) => game,
+const DEFAULT_DATABASE_URL: &str = "sqlite://save_data/game.db?mode=rwc";
+const LOCAL_PROFILE_ID: u64 = 0;
+
+#[tokio::main]
+async fn main() {
+  let database_url = std::env::var("DATABASE_URL")
+    .unwrap_or_else(|_| DEFAULT_DATABASE_URL.to_string());
+  let storage = Storage::connect(&database_url)
+    .await
+    .expect("Couldn't open the profile database");
+
+  let mut game = match storage.load_profile(LOCAL_PROFILE_ID).await {
+    Ok(Some(game)) => game,
+    Ok(None) => GameData::default(),
     Err(why) => {
-      println!("Couldn't parse save file: {why:?}");
+      println!("Couldn't load profile data: {why}");
       GameData::default()
     }
   };
@@ -36,26 +37,16 @@ fn main() {
     stdout_lock.write(response.as_bytes()).ok();
     stdout_lock.write(b"\n\n").ok();
     stdout_lock.flush().ok();
+    if let Err(why) = storage.store_profile(LOCAL_PROFILE_ID, &game).await {
+      println!("Couldn't save profile data: {why}");
+      return;
+    }
+    if let Err(why) = storage
+      .record_turn(LOCAL_PROFILE_ID, game.turn_index(), &in_buf, &response)
+      .await
+    {
+      println!("Couldn't save turn history: {why}");
+    }
     in_buf.clear();
-    let profile_bytes = match Vec::<u8>::try_from(&game) {
-      Ok(bytes) => bytes,
-      Err(why) => {
-        println!("Couldn't serialize profile data: {why}");
-        return;
-      }
-    };
-    store_profile_bytes(&profile_bytes).unwrap();
   }
 }
-
-fn save_path() -> PathBuf {
-  Path::new("save_data").join("local_save.data")
-}
-fn load_profile_bytes() -> std::io::Result<Vec<u8>> {
-  std::fs::read(save_path())
-}
-fn store_profile_bytes(bytes: &[u8]) -> std::io::Result<()> {
-  let p = save_path();
-  std::fs::create_dir_all(p.parent().unwrap_or(Path::new(""))).ok();
-  std::fs::write(p, bytes)
-}
@@ -0,0 +1,91 @@
+//! Player accounts, decoupled from any single Discord channel.
+//!
+//! A [`PlayerId`] identifies a registered player independent of the
+//! `ChannelId` they happen to be messaging from, so the same save can be
+//! resumed from any DM once the player logs in.
+
+use argon2::{
+  password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+  Argon2,
+};
+use rand_core::OsRng;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct PlayerId(pub(crate) i64);
+
+/// Errors from the accounts subsystem.
+///
+/// `InvalidCredentials` covers both "no such player" and "wrong password" so
+/// a failed login never reveals whether the name is registered.
+#[derive(Debug)]
+pub enum AccountError {
+  NameTaken,
+  InvalidCredentials,
+  Storage(crate::StorageError),
+  Hash(argon2::password_hash::Error),
+}
+impl From<crate::StorageError> for AccountError {
+  fn from(value: crate::StorageError) -> Self {
+    AccountError::Storage(value)
+  }
+}
+impl From<argon2::password_hash::Error> for AccountError {
+  fn from(value: argon2::password_hash::Error) -> Self {
+    AccountError::Hash(value)
+  }
+}
+impl core::fmt::Display for AccountError {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    match self {
+      AccountError::NameTaken => write!(f, "that name is already registered"),
+      AccountError::InvalidCredentials => write!(f, "invalid name or password"),
+      AccountError::Storage(why) => write!(f, "{why}"),
+      AccountError::Hash(why) => write!(f, "password hashing error: {why}"),
+    }
+  }
+}
+impl std::error::Error for AccountError {}
+
+/// Hashes `password` into a salted PHC string suitable for storing in the
+/// `players.pw_hash` column.
+pub(crate) fn hash_password(password: &str) -> Result<String, AccountError> {
+  let salt = SaltString::generate(&mut OsRng);
+  let hash = Argon2::default().hash_password(password.as_bytes(), &salt)?;
+  Ok(hash.to_string())
+}
+
+/// Verifies `password` against a stored PHC hash in constant time. Returns
+/// `false` (rather than erroring) on a malformed hash, since that should
+/// never be reachable except via database corruption.
+pub(crate) fn verify_password(password: &str, pw_hash: &str) -> bool {
+  match PasswordHash::new(pw_hash) {
+    Ok(parsed) => {
+      Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok()
+    }
+    Err(_) => false,
+  }
+}
+
+/// A PHC hash nobody's password will ever match, computed once and reused.
+///
+/// [`Storage::login_player`](crate::Storage::login_player) runs
+/// `verify_password` against this when the name isn't registered, so a login
+/// attempt against an unknown name costs the same argon2 work as one against
+/// a real account with a wrong password — otherwise the response time itself
+/// would reveal whether the name exists.
+pub(crate) fn dummy_password_hash() -> &'static str {
+  static HASH: OnceLock<String> = OnceLock::new();
+  HASH.get_or_init(|| {
+    hash_password("no account uses this password")
+      .expect("hashing a fixed password can't fail")
+  })
+}
+
+#[test]
+fn test_hash_and_verify_roundtrip() {
+  let hash = hash_password("correct horse battery staple").unwrap();
+  assert!(verify_password("correct horse battery staple", &hash));
+  assert!(!verify_password("wrong password", &hash));
+}
@@ -1,12 +1,50 @@
 #![cfg_attr(test, feature(is_sorted))]
 
-use std::{fmt::Write, num::NonZeroU32};
+use std::{
+  fmt::Write,
+  num::NonZeroU32,
+  time::{SystemTime, UNIX_EPOCH},
+};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+mod accounts;
+pub use accounts::{AccountError, PlayerId};
+
+mod storage;
+pub use storage::{Storage, StorageError};
+
+mod metrics;
+pub use metrics::MetricRegistry;
+
+/// One recorded turn of play: the raw input, the response it produced, and
+/// when it happened (seconds since the Unix epoch).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Turn {
+  pub input: String,
+  pub response: String,
+  pub timestamp: i64,
+}
+
+/// How many recent turns a live session keeps in memory for the `history`
+/// command. Full history is persisted separately via [`Storage`].
+const MAX_IN_MEMORY_HISTORY: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameData {
   message_count: u64,
+  current_room: RoomID,
+  #[serde(skip)]
+  history: Vec<Turn>,
+}
+impl Default for GameData {
+  fn default() -> Self {
+    GameData {
+      message_count: 0,
+      current_room: room_id("d101"),
+      history: Vec::new(),
+    }
+  }
 }
 
 impl TryFrom<&[u8]> for GameData {
@@ -23,9 +61,122 @@ impl TryFrom<&GameData> for Vec<u8> {
 }
 
 impl GameData {
-  pub fn process_input(&mut self, _input: &str) -> String {
+  pub fn process_input(&mut self, input: &str) -> String {
     self.message_count += 1;
-    format!("{}", self.message_count)
+    let response = match Command::parse(input) {
+      Command::Look => self.look(),
+      Command::Go(dir) => self.go(dir),
+      Command::Take(item) => {
+        format!("There's nothing here called '{item}' to take.")
+      }
+      Command::Drop(item) => format!("You aren't carrying a '{item}'."),
+      Command::Inventory => "You aren't carrying anything.".to_string(),
+      Command::Say(text) => format!("You say, \"{text}\""),
+      Command::Help => Command::help_text(),
+      Command::History { limit } => self.history_text(limit),
+      Command::Register { .. } => {
+        "This frontend doesn't support accounts; use the Discord bot to \
+         register."
+          .to_string()
+      }
+      Command::Login { .. } => {
+        "This frontend doesn't support accounts; use the Discord bot to \
+         log in."
+          .to_string()
+      }
+      Command::Unknown(raw) => format!("I don't understand '{raw}'."),
+    };
+    self.record_turn(input, &response);
+    response
+  }
+
+  /// Returns this session's `turn_index`, the same counter used to key rows
+  /// in the persisted `turns` table.
+  pub fn turn_index(&self) -> u64 {
+    self.message_count
+  }
+
+  /// The id of the room the player currently occupies.
+  pub fn current_room_id(&self) -> RoomID {
+    self.current_room
+  }
+
+  fn record_turn(&mut self, input: &str, response: &str) {
+    let timestamp = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_secs() as i64)
+      .unwrap_or(0);
+    self.history.push(Turn {
+      input: input.to_string(),
+      response: response.to_string(),
+      timestamp,
+    });
+    if self.history.len() > MAX_IN_MEMORY_HISTORY {
+      self.history.remove(0);
+    }
+  }
+
+  /// Returns (at most) the last `limit` turns of this in-memory session,
+  /// oldest first.
+  pub fn recent_turns(&self, limit: usize) -> Vec<Turn> {
+    let start = self.history.len().saturating_sub(limit);
+    self.history[start..].to_vec()
+  }
+
+  /// Seeds this session's in-memory history from persisted turns (oldest
+  /// first, the same order [`Storage::recent_turns`](crate::Storage::recent_turns)
+  /// returns them in), so a reconnecting player's `history` command sees
+  /// turns from before this session's actor was last torn down. Capped at
+  /// `MAX_IN_MEMORY_HISTORY` like any other history growth.
+  pub fn seed_history(&mut self, turns: Vec<Turn>) {
+    let start = turns.len().saturating_sub(MAX_IN_MEMORY_HISTORY);
+    self.history = turns[start..].to_vec();
+  }
+
+  fn history_text(&self, limit: u32) -> String {
+    let turns = self.recent_turns(limit as usize);
+    if turns.is_empty() {
+      return "No history yet.".to_string();
+    }
+    let mut out = String::new();
+    for turn in turns.iter() {
+      writeln!(out, "> {}\n{}", turn.input, turn.response).ok();
+    }
+    out.trim_end().to_string()
+  }
+
+  fn current_room(&self) -> &'static Room {
+    find_room(self.current_room).unwrap_or(&Room::DEFAULT)
+  }
+
+  fn look(&self) -> String {
+    let room = self.current_room();
+    let mut out = format!("{}\n{}", room.name, room.description);
+    if room.exits.is_empty() {
+      write!(out, "\nThere are no obvious exits.").ok();
+    } else {
+      write!(out, "\nExits:").ok();
+      for (dir, _) in room.exits.iter() {
+        write!(out, " {}", dir.name()).ok();
+      }
+    }
+    out
+  }
+
+  fn go(&mut self, dir: Direction) -> String {
+    let room = self.current_room();
+    match room.exits.iter().find(|(d, _)| *d == dir) {
+      Some((_, target)) => match find_room(*target) {
+        Some(next) => {
+          self.current_room = next.id;
+          format!("You go {}.\n\n{}", dir.name(), self.look())
+        }
+        None => {
+          format!("The way {} leads nowhere (unconfigured room).", dir.name())
+        }
+      },
+      None => format!("You can't go {} from here.", dir.name()),
+    }
   }
 }
 
@@ -63,11 +214,146 @@ pub const fn room_id(s: &str) -> RoomID {
 
 type StrLit = &'static str;
 
+/// A parsed, typed player command.
+///
+/// Raw input always goes through [`Command::parse`] before `GameData` acts on
+/// it, so each verb can be unit tested on its own and dispatch is a single
+/// `match` with no further string wrangling.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+  Look,
+  Go(Direction),
+  Take(String),
+  Drop(String),
+  Inventory,
+  Say(String),
+  Help,
+  History { limit: u32 },
+  Register { name: String, password: String },
+  Login { name: String, password: String },
+  Unknown(String),
+}
+impl Command {
+  pub fn parse(input: &str) -> Command {
+    let trimmed = input.trim();
+    let lower = trimmed.to_ascii_lowercase();
+    let mut parts = lower.splitn(2, ' ');
+    let verb = parts.next().unwrap_or("");
+    let rest = parts.next().unwrap_or("").trim();
+    // Passwords must keep their original case, so `register`/`login` split
+    // the un-lowercased tail instead of `rest`.
+    let raw_rest = trimmed.get(verb.len()..).unwrap_or("").trim();
+    match verb {
+      "register" | "login" => {
+        // Split on the first run of whitespace, not just the first space, so
+        // `register newt  hunter2` doesn't leak a leading space into the
+        // password.
+        let mut raw_parts = raw_rest.splitn(2, char::is_whitespace);
+        let name = raw_parts.next();
+        let password = raw_parts.next().map(str::trim_start);
+        match (name, password) {
+          (Some(name), Some(password))
+            if !name.is_empty() && !password.is_empty() =>
+          {
+            let name = name.to_string();
+            let password = password.to_string();
+            if verb == "register" {
+              Command::Register { name, password }
+            } else {
+              Command::Login { name, password }
+            }
+          }
+          _ => Command::Unknown(trimmed.to_string()),
+        }
+      }
+      "look" | "l" | "where" => Command::Look,
+      "go" => match Direction::parse(rest) {
+        Some(dir) => Command::Go(dir),
+        None => Command::Unknown(trimmed.to_string()),
+      },
+      "n" | "north" => Command::Go(Direction::North),
+      "s" | "south" => Command::Go(Direction::South),
+      "e" | "east" => Command::Go(Direction::East),
+      "w" | "west" => Command::Go(Direction::West),
+      "u" | "up" => Command::Go(Direction::Up),
+      "d" | "down" => Command::Go(Direction::Down),
+      "take" | "get" => Command::Take(rest.to_string()),
+      "drop" => Command::Drop(rest.to_string()),
+      "inventory" | "inv" | "i" => Command::Inventory,
+      "say" => Command::Say(rest.to_string()),
+      "help" | "?" => Command::Help,
+      "history" | "hist" => {
+        let limit = rest.parse::<u32>().unwrap_or(Command::DEFAULT_HISTORY_LIMIT);
+        Command::History { limit }
+      }
+      _ => Command::Unknown(trimmed.to_string()),
+    }
+  }
+
+  const DEFAULT_HISTORY_LIMIT: u32 = 10;
+
+  pub fn help_text() -> String {
+    "Available commands: look, go <direction>, take <item>, drop <item>, \
+     inventory, say <text>, history [n], register <name> <password>, \
+     login <name> <password>, help"
+      .to_string()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Direction {
+  North,
+  South,
+  East,
+  West,
+  Up,
+  Down,
+}
+impl Direction {
+  pub const fn name(self) -> StrLit {
+    match self {
+      Direction::North => "north",
+      Direction::South => "south",
+      Direction::East => "east",
+      Direction::West => "west",
+      Direction::Up => "up",
+      Direction::Down => "down",
+    }
+  }
+
+  /// Parses a direction from a full name or its single-letter alias.
+  pub fn parse(s: &str) -> Option<Direction> {
+    match s {
+      "n" | "north" => Some(Direction::North),
+      "s" | "south" => Some(Direction::South),
+      "e" | "east" => Some(Direction::East),
+      "w" | "west" => Some(Direction::West),
+      "u" | "up" => Some(Direction::Up),
+      "d" | "down" => Some(Direction::Down),
+      _ => None,
+    }
+  }
+
+  /// The direction you'd have to travel to undo this one, e.g. someone who
+  /// goes `North` into a room arrived *from* the `South`.
+  pub const fn opposite(self) -> Direction {
+    match self {
+      Direction::North => Direction::South,
+      Direction::South => Direction::North,
+      Direction::East => Direction::West,
+      Direction::West => Direction::East,
+      Direction::Up => Direction::Down,
+      Direction::Down => Direction::Up,
+    }
+  }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Room {
   pub id: RoomID,
   pub name: StrLit,
   pub description: StrLit,
+  pub exits: &'static [(Direction, RoomID)],
 }
 impl Default for Room {
   fn default() -> Self {
@@ -75,30 +361,176 @@ impl Default for Room {
   }
 }
 impl Room {
-  pub const DEFAULT: Room =
-    Room { id: room_id("DEAD"), name: "Default", description: "Default" };
+  pub const DEFAULT: Room = Room {
+    id: room_id("DEAD"),
+    name: "Default",
+    description: "Default",
+    exits: &[],
+  };
+}
+
+/// Finds the [`Room`] with a given id, binary searching `ROOM_DB` since it's
+/// kept sorted by [`RoomID`].
+pub fn find_room(id: RoomID) -> Option<&'static Room> {
+  ROOM_DB.binary_search_by_key(&id, |room| room.id).ok().map(|i| &ROOM_DB[i])
 }
 
 pub const ROOM_DB: &[Room] = &[
   Room { id: room_id("c_H8"), name: "Shrine of Resurrection", ..Room::DEFAULT },
-  Room { id: room_id("d101"), name: "entry", ..Room::DEFAULT },
-  Room { id: room_id("d102"), name: "deadend", ..Room::DEFAULT },
-  Room { id: room_id("d103"), name: "deadend", ..Room::DEFAULT },
-  Room { id: room_id("d104"), name: "hall-north", ..Room::DEFAULT },
-  Room { id: room_id("d105"), name: "room", ..Room::DEFAULT },
-  Room { id: room_id("d106"), name: "room", ..Room::DEFAULT },
-  Room { id: room_id("d107"), name: "room", ..Room::DEFAULT },
-  Room { id: room_id("d108"), name: "room", ..Room::DEFAULT },
-  Room { id: room_id("d109"), name: "room", ..Room::DEFAULT },
-  Room { id: room_id("d110"), name: "room", ..Room::DEFAULT },
-  Room { id: room_id("d111"), name: "hall", ..Room::DEFAULT },
-  Room { id: room_id("d112"), name: "hall-turn-west", ..Room::DEFAULT },
-  Room { id: room_id("d113"), name: "stairs-down", ..Room::DEFAULT },
-  Room { id: room_id("d114"), name: "mini-treasure?", ..Room::DEFAULT },
-  Room { id: room_id("d115"), name: "sage?", ..Room::DEFAULT },
-  Room { id: room_id("d116"), name: "hall-turn-north", ..Room::DEFAULT },
-  Room { id: room_id("d117"), name: "boss-fight", ..Room::DEFAULT },
-  Room { id: room_id("d118"), name: "the-cape", ..Room::DEFAULT },
+  Room {
+    id: room_id("d101"),
+    name: "entry",
+    exits: &[
+      (Direction::North, room_id("d104")),
+      (Direction::East, room_id("d102")),
+      (Direction::West, room_id("d103")),
+    ],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d102"),
+    name: "deadend",
+    exits: &[(Direction::West, room_id("d101"))],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d103"),
+    name: "deadend",
+    exits: &[(Direction::East, room_id("d101"))],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d104"),
+    name: "hall-north",
+    exits: &[
+      (Direction::South, room_id("d101")),
+      (Direction::North, room_id("d105")),
+    ],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d105"),
+    name: "room",
+    exits: &[
+      (Direction::South, room_id("d104")),
+      (Direction::North, room_id("d106")),
+    ],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d106"),
+    name: "room",
+    exits: &[
+      (Direction::South, room_id("d105")),
+      (Direction::North, room_id("d107")),
+    ],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d107"),
+    name: "room",
+    exits: &[
+      (Direction::South, room_id("d106")),
+      (Direction::North, room_id("d108")),
+    ],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d108"),
+    name: "room",
+    exits: &[
+      (Direction::South, room_id("d107")),
+      (Direction::North, room_id("d109")),
+    ],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d109"),
+    name: "room",
+    exits: &[
+      (Direction::South, room_id("d108")),
+      (Direction::North, room_id("d110")),
+    ],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d110"),
+    name: "room",
+    exits: &[
+      (Direction::South, room_id("d109")),
+      (Direction::North, room_id("d111")),
+    ],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d111"),
+    name: "hall",
+    exits: &[
+      (Direction::South, room_id("d110")),
+      (Direction::West, room_id("d112")),
+    ],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d112"),
+    name: "hall-turn-west",
+    exits: &[
+      (Direction::East, room_id("d111")),
+      (Direction::North, room_id("d113")),
+    ],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d113"),
+    name: "stairs-down",
+    exits: &[
+      (Direction::South, room_id("d112")),
+      (Direction::Down, room_id("d114")),
+    ],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d114"),
+    name: "mini-treasure?",
+    exits: &[
+      (Direction::Up, room_id("d113")),
+      (Direction::North, room_id("d115")),
+    ],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d115"),
+    name: "sage?",
+    exits: &[
+      (Direction::South, room_id("d114")),
+      (Direction::North, room_id("d116")),
+    ],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d116"),
+    name: "hall-turn-north",
+    exits: &[
+      (Direction::South, room_id("d115")),
+      (Direction::North, room_id("d117")),
+    ],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d117"),
+    name: "boss-fight",
+    exits: &[
+      (Direction::South, room_id("d116")),
+      (Direction::North, room_id("d118")),
+    ],
+    ..Room::DEFAULT
+  },
+  Room {
+    id: room_id("d118"),
+    name: "the-cape",
+    exits: &[(Direction::South, room_id("d117"))],
+    ..Room::DEFAULT
+  },
   Room { id: room_id("w_H4"), name: "Zakros Isle", ..Room::DEFAULT },
   Room { id: room_id("w_H8"), name: "Firros", ..Room::DEFAULT },
   Room { id: room_id("w_I4"), name: "Baikal", ..Room::DEFAULT },
@@ -108,6 +540,21 @@ pub const ROOM_DB: &[Room] = &[
   Room { id: room_id("w_I8"), name: "Torshavn", ..Room::DEFAULT },
 ];
 
+#[test]
+fn test_all_exits_resolve_to_real_rooms() {
+  for room in ROOM_DB.iter() {
+    for (dir, target) in room.exits.iter() {
+      assert!(
+        find_room(*target).is_some(),
+        "{:?} exit {:?} points at missing room {:?}",
+        room.id,
+        dir,
+        target
+      );
+    }
+  }
+}
+
 #[test]
 fn test_room_db_sorted() {
   assert!(ROOM_DB.is_sorted(), "ROOM_DB not sorted! Should be: {:?}", {
@@ -126,3 +573,141 @@ fn test_all_room_ids_different() {
   }
   assert_eq!(ROOM_DB.len(), set.len());
 }
+
+#[test]
+fn test_command_parse_look_and_where() {
+  assert_eq!(Command::parse("look"), Command::Look);
+  assert_eq!(Command::parse("  Look  "), Command::Look);
+  assert_eq!(Command::parse("where"), Command::Look);
+}
+
+#[test]
+fn test_command_parse_go() {
+  assert_eq!(Command::parse("go north"), Command::Go(Direction::North));
+  assert_eq!(Command::parse("n"), Command::Go(Direction::North));
+  assert_eq!(Command::parse("GO DOWN"), Command::Go(Direction::Down));
+  assert_eq!(
+    Command::parse("go sideways"),
+    Command::Unknown("go sideways".to_string())
+  );
+}
+
+#[test]
+fn test_direction_opposite() {
+  assert_eq!(Direction::North.opposite(), Direction::South);
+  assert_eq!(Direction::Up.opposite(), Direction::Down);
+  assert_eq!(Direction::East.opposite().opposite(), Direction::East);
+}
+
+#[test]
+fn test_command_parse_items() {
+  assert_eq!(Command::parse("take sword"), Command::Take("sword".to_string()));
+  assert_eq!(Command::parse("get sword"), Command::Take("sword".to_string()));
+  assert_eq!(Command::parse("drop sword"), Command::Drop("sword".to_string()));
+  assert_eq!(Command::parse("inventory"), Command::Inventory);
+  assert_eq!(Command::parse("i"), Command::Inventory);
+}
+
+#[test]
+fn test_command_parse_say_and_help() {
+  assert_eq!(
+    Command::parse("say hello there"),
+    Command::Say("hello there".to_string())
+  );
+  assert_eq!(Command::parse("help"), Command::Help);
+  assert_eq!(Command::parse("?"), Command::Help);
+}
+
+#[test]
+fn test_command_parse_unknown() {
+  assert_eq!(
+    Command::parse("dance"),
+    Command::Unknown("dance".to_string())
+  );
+}
+
+#[test]
+fn test_command_parse_history() {
+  assert_eq!(Command::parse("history"), Command::History { limit: 10 });
+  assert_eq!(Command::parse("history 20"), Command::History { limit: 20 });
+  assert_eq!(Command::parse("hist 3"), Command::History { limit: 3 });
+}
+
+#[test]
+fn test_command_parse_register_and_login_keep_password_case() {
+  assert_eq!(
+    Command::parse("register Newt Sw0rdFish"),
+    Command::Register {
+      name: "Newt".to_string(),
+      password: "Sw0rdFish".to_string()
+    }
+  );
+  assert_eq!(
+    Command::parse("LOGIN Newt Sw0rdFish"),
+    Command::Login {
+      name: "Newt".to_string(),
+      password: "Sw0rdFish".to_string()
+    }
+  );
+  assert_eq!(
+    Command::parse("register onlyname"),
+    Command::Unknown("register onlyname".to_string())
+  );
+}
+
+#[test]
+fn test_command_parse_register_ignores_extra_inter_token_spaces() {
+  assert_eq!(
+    Command::parse("register newt  hunter2"),
+    Command::Register {
+      name: "newt".to_string(),
+      password: "hunter2".to_string()
+    }
+  );
+  assert_eq!(
+    Command::parse("login newt\thunter2"),
+    Command::Login {
+      name: "newt".to_string(),
+      password: "hunter2".to_string()
+    }
+  );
+}
+
+#[test]
+fn test_recent_turns_ordering() {
+  let mut game = GameData::default();
+  game.process_input("look");
+  game.process_input("n");
+  game.process_input("say hi");
+
+  let turns = game.recent_turns(2);
+  assert_eq!(turns.len(), 2);
+  assert_eq!(turns[0].input, "n");
+  assert_eq!(turns[1].input, "say hi");
+}
+
+#[test]
+fn test_recent_turns_more_than_exist() {
+  let mut game = GameData::default();
+  game.process_input("look");
+  game.process_input("where");
+
+  let turns = game.recent_turns(50);
+  assert_eq!(turns.len(), 2);
+  assert_eq!(turns[0].input, "look");
+  assert_eq!(turns[1].input, "where");
+}
+
+#[test]
+fn test_seed_history_then_history_command() {
+  let mut game = GameData::default();
+  game.seed_history(vec![Turn {
+    input: "look".to_string(),
+    response: "entry".to_string(),
+    timestamp: 0,
+  }]);
+
+  let response = game.process_input("history");
+  assert!(response.contains("look"));
+  assert!(response.contains("entry"));
+}
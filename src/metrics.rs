@@ -0,0 +1,99 @@
+//! Runtime metrics, scraped by Prometheus over a plain `/metrics` endpoint.
+//!
+//! This gives operators visibility into concurrent players and per-turn cost
+//! without adding log noise. The live-session gauge tracks the
+//! `SessionsMap` invariant documented on `TextBot`: it is incremented when a
+//! session is inserted into that map and decremented when it is removed, so
+//! it equals the number of entries currently held there.
+
+use prometheus::{
+  Encoder, Histogram, HistogramOpts, IntCounter, IntGauge, Opts, Registry,
+  TextEncoder,
+};
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt},
+  net::TcpListener,
+};
+
+/// A handle to the process's Prometheus metrics.
+///
+/// Every field here (the `Registry` and each collector) is an `Arc`-backed
+/// handle internally, so all clones of a `MetricRegistry` record into the
+/// same underlying counters — the metrics endpoint and every session task
+/// observe one shared set of numbers, not a copy each.
+#[derive(Clone)]
+pub struct MetricRegistry {
+  registry: Registry,
+  pub live_sessions: IntGauge,
+  pub inputs_total: IntCounter,
+  pub process_input_latency: Histogram,
+}
+impl MetricRegistry {
+  pub fn new() -> Self {
+    let registry = Registry::new();
+
+    let live_sessions = IntGauge::with_opts(Opts::new(
+      "rpgsanta_live_sessions",
+      "Number of currently live game sessions",
+    ))
+    .unwrap();
+    let inputs_total = IntCounter::with_opts(Opts::new(
+      "rpgsanta_inputs_total",
+      "Total number of inputs processed across all sessions",
+    ))
+    .unwrap();
+    let process_input_latency = Histogram::with_opts(HistogramOpts::new(
+      "rpgsanta_process_input_latency_seconds",
+      "Latency of GameData::process_input calls, in seconds",
+    ))
+    .unwrap();
+
+    registry.register(Box::new(live_sessions.clone())).unwrap();
+    registry.register(Box::new(inputs_total.clone())).unwrap();
+    registry.register(Box::new(process_input_latency.clone())).unwrap();
+
+    MetricRegistry {
+      registry,
+      live_sessions,
+      inputs_total,
+      process_input_latency,
+    }
+  }
+
+  fn encode(&self) -> Vec<u8> {
+    let metric_families = self.registry.gather();
+    let mut buf = Vec::new();
+    TextEncoder::new().encode(&metric_families, &mut buf).ok();
+    buf
+  }
+
+  /// Serves the current metrics as plain-text Prometheus exposition format
+  /// on every connection to `bind_addr`, regardless of the request path.
+  /// Returns only if binding the listener or accepting a connection fails.
+  pub async fn serve(&self, bind_addr: &str) -> std::io::Result<()> {
+    let listener = TcpListener::bind(bind_addr).await?;
+    loop {
+      let (mut stream, _) = listener.accept().await?;
+      let metrics = self.clone();
+      tokio::spawn(async move {
+        let mut buf = [0u8; 1024];
+        if stream.read(&mut buf).await.is_err() {
+          return;
+        }
+        let body = metrics.encode();
+        let header = format!(
+          "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+           Content-Length: {}\r\nConnection: close\r\n\r\n",
+          body.len()
+        );
+        let _ = stream.write_all(header.as_bytes()).await;
+        let _ = stream.write_all(&body).await;
+      });
+    }
+  }
+}
+impl Default for MetricRegistry {
+  fn default() -> Self {
+    Self::new()
+  }
+}
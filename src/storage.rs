@@ -0,0 +1,227 @@
+//! A single SQLite-backed store shared by every frontend binary.
+//!
+//! This replaces the old pattern of each binary hand-rolling
+//! `std::fs::read`/`write` of a bincode blob per channel id. Migrations live
+//! under `migrations/` at the crate root and are embedded into the binary at
+//! compile time, so `Storage::connect` always leaves the database on the
+//! latest schema.
+
+use crate::accounts::{dummy_password_hash, hash_password, verify_password};
+use crate::{AccountError, GameData, PlayerId, Turn};
+use sqlx::{sqlite::SqlitePoolOptions, Pool, Sqlite};
+
+/// Errors that can happen while talking to the profile store.
+#[derive(Debug)]
+pub enum StorageError {
+  Sqlx(sqlx::Error),
+  Bincode(Box<bincode::ErrorKind>),
+}
+impl From<sqlx::Error> for StorageError {
+  fn from(value: sqlx::Error) -> Self {
+    StorageError::Sqlx(value)
+  }
+}
+impl From<Box<bincode::ErrorKind>> for StorageError {
+  fn from(value: Box<bincode::ErrorKind>) -> Self {
+    StorageError::Bincode(value)
+  }
+}
+impl core::fmt::Display for StorageError {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    match self {
+      StorageError::Sqlx(why) => write!(f, "storage error: {why}"),
+      StorageError::Bincode(why) => write!(f, "profile encoding error: {why}"),
+    }
+  }
+}
+impl std::error::Error for StorageError {}
+
+/// Maps a [`PlayerId`] into the `turns.channel_id` space with the sign bit
+/// set, so a real `ChannelId` (always a small positive snowflake) can never
+/// collide with it.
+fn player_turn_key(player: PlayerId) -> i64 {
+  (1u64 << 63 | player.0 as u64) as i64
+}
+
+/// A handle to the profile database.
+///
+/// `sqlx::Pool` is itself a cheap-to-clone handle backed by an `Arc`, so
+/// cloning a `Storage` is just a pool clone, not a new connection — every
+/// frontend binary hands a copy of the same `Storage` to each of its async
+/// tasks rather than reconnecting per task.
+#[derive(Clone)]
+pub struct Storage {
+  pool: Pool<Sqlite>,
+}
+impl Storage {
+  /// Connects to (and if necessary creates) the database at `database_url`,
+  /// then brings its schema up to date via the embedded migrations.
+  pub async fn connect(database_url: &str) -> Result<Self, StorageError> {
+    let pool = SqlitePoolOptions::new().connect(database_url).await?;
+    sqlx::migrate!("./migrations").run(&pool).await?;
+    Ok(Storage { pool })
+  }
+
+  pub async fn load_profile(
+    &self, channel_id: u64,
+  ) -> Result<Option<GameData>, StorageError> {
+    let row: Option<(Vec<u8>,)> =
+      sqlx::query_as("SELECT data FROM profiles WHERE channel_id = ?1")
+        .bind(channel_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+    match row {
+      Some((bytes,)) => Ok(Some(GameData::try_from(bytes.as_slice())?)),
+      None => Ok(None),
+    }
+  }
+
+  pub async fn store_profile(
+    &self, channel_id: u64, game: &GameData,
+  ) -> Result<(), StorageError> {
+    let bytes = Vec::<u8>::try_from(game)?;
+    sqlx::query(
+      "INSERT INTO profiles (channel_id, data, updated_at) \
+       VALUES (?1, ?2, CURRENT_TIMESTAMP) \
+       ON CONFLICT(channel_id) DO UPDATE SET \
+         data = excluded.data, updated_at = excluded.updated_at",
+    )
+    .bind(channel_id as i64)
+    .bind(bytes)
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  /// Persists a single turn of an anonymous, channel-keyed session.
+  /// `turn_index` should come from [`GameData::turn_index`] so rows stay
+  /// ordered per channel.
+  pub async fn record_turn(
+    &self, channel_id: u64, turn_index: u64, input: &str, response: &str,
+  ) -> Result<(), StorageError> {
+    self.insert_turn(channel_id as i64, turn_index, input, response).await
+  }
+
+  /// Persists a single turn for an authenticated player, namespaced under
+  /// [`player_turn_key`] so it can never collide with an anonymous channel's
+  /// `(channel_id, turn_index)` rows even if that channel logs in mid-session
+  /// with a lower `turn_index` than it already recorded anonymously.
+  pub async fn record_player_turn(
+    &self, player: PlayerId, turn_index: u64, input: &str, response: &str,
+  ) -> Result<(), StorageError> {
+    self.insert_turn(player_turn_key(player), turn_index, input, response).await
+  }
+
+  async fn insert_turn(
+    &self, subject_id: i64, turn_index: u64, input: &str, response: &str,
+  ) -> Result<(), StorageError> {
+    sqlx::query(
+      "INSERT INTO turns (channel_id, turn_index, input, response) \
+       VALUES (?1, ?2, ?3, ?4)",
+    )
+    .bind(subject_id)
+    .bind(turn_index as i64)
+    .bind(input)
+    .bind(response)
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  /// Returns the last `limit` persisted turns for `channel_id`, oldest first,
+  /// so a reconnecting player can see where they left off.
+  pub async fn recent_turns(
+    &self, channel_id: u64, limit: u32,
+  ) -> Result<Vec<Turn>, StorageError> {
+    let rows: Vec<(String, String, i64)> = sqlx::query_as(
+      "SELECT input, response, CAST(strftime('%s', created_at) AS INTEGER) \
+       FROM turns WHERE channel_id = ?1 \
+       ORDER BY turn_index DESC LIMIT ?2",
+    )
+    .bind(channel_id as i64)
+    .bind(limit)
+    .fetch_all(&self.pool)
+    .await?;
+    let mut turns: Vec<Turn> = rows
+      .into_iter()
+      .map(|(input, response, timestamp)| Turn { input, response, timestamp })
+      .collect();
+    turns.reverse();
+    Ok(turns)
+  }
+
+  /// Registers a new player with an argon2-hashed password. The plaintext
+  /// password is never stored.
+  pub async fn register_player(
+    &self, name: &str, password: &str,
+  ) -> Result<PlayerId, AccountError> {
+    let pw_hash = hash_password(password)?;
+    let result = sqlx::query("INSERT INTO players (name, pw_hash) VALUES (?1, ?2)")
+      .bind(name)
+      .bind(pw_hash)
+      .execute(&self.pool)
+      .await;
+    match result {
+      Ok(done) => Ok(PlayerId(done.last_insert_rowid())),
+      Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+        Err(AccountError::NameTaken)
+      }
+      Err(why) => Err(StorageError::from(why).into()),
+    }
+  }
+
+  /// Verifies `name`/`password` against the stored argon2 hash. Returns
+  /// [`AccountError::InvalidCredentials`] uniformly whether the name doesn't
+  /// exist or the password doesn't match, so a failed login never reveals
+  /// which one it was.
+  pub async fn login_player(
+    &self, name: &str, password: &str,
+  ) -> Result<PlayerId, AccountError> {
+    let row: Option<(i64, String)> =
+      sqlx::query_as("SELECT id, pw_hash FROM players WHERE name = ?1")
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(StorageError::from)?;
+    match row {
+      Some((id, pw_hash)) if verify_password(password, &pw_hash) => {
+        Ok(PlayerId(id))
+      }
+      Some(_) => Err(AccountError::InvalidCredentials),
+      None => {
+        // Still pay the argon2 cost so a nonexistent name doesn't respond
+        // faster than a wrong password would.
+        verify_password(password, dummy_password_hash());
+        Err(AccountError::InvalidCredentials)
+      }
+    }
+  }
+
+  pub async fn load_player_profile(
+    &self, player: PlayerId,
+  ) -> Result<Option<GameData>, StorageError> {
+    let row: Option<(Vec<u8>,)> = sqlx::query_as(
+      "SELECT profile_blob FROM players \
+       WHERE id = ?1 AND profile_blob IS NOT NULL",
+    )
+    .bind(player.0)
+    .fetch_optional(&self.pool)
+    .await?;
+    match row {
+      Some((bytes,)) => Ok(Some(GameData::try_from(bytes.as_slice())?)),
+      None => Ok(None),
+    }
+  }
+
+  pub async fn store_player_profile(
+    &self, player: PlayerId, game: &GameData,
+  ) -> Result<(), StorageError> {
+    let bytes = Vec::<u8>::try_from(game)?;
+    sqlx::query("UPDATE players SET profile_blob = ?1 WHERE id = ?2")
+      .bind(bytes)
+      .bind(player.0)
+      .execute(&self.pool)
+      .await?;
+    Ok(())
+  }
+}